@@ -0,0 +1,105 @@
+use uuid::Uuid;
+
+use crate::SensorData;
+
+use super::{sig_uuid16, AdvDecoder};
+
+// BTHome v2 object IDs this decoder understands.
+// See https://bthome.io/format/ for the full register.
+const OBJ_PACKET_ID: u8 = 0x00;
+const OBJ_BATTERY: u8 = 0x01;
+const OBJ_TEMPERATURE: u8 = 0x02;
+const OBJ_HUMIDITY: u8 = 0x03;
+const OBJ_VOLTAGE: u8 = 0x0C;
+
+/// Decodes BTHome v2 service-data payloads (service UUID 0xFCD2).
+pub struct BthomeDecoder {
+    uuids: Vec<Uuid>,
+}
+
+impl BthomeDecoder {
+    pub fn new() -> Self {
+        Self {
+            uuids: vec![sig_uuid16(0xFCD2)],
+        }
+    }
+}
+
+impl Default for BthomeDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdvDecoder for BthomeDecoder {
+    fn service_uuids(&self) -> &[Uuid] {
+        &self.uuids
+    }
+
+    fn decode(&self, data: &[u8]) -> Option<SensorData> {
+        let (&flags, records) = data.split_first()?;
+
+        // Bit 0 of the device-info flags byte marks the payload as
+        // encrypted; we don't support decrypting it.
+        if flags & 0x01 != 0 {
+            return None;
+        }
+
+        let mut sensor_data = SensorData {
+            mac_address: String::new(),
+            temperature: None,
+            humidity: None,
+            battery_level: None,
+            battery_voltage: None,
+            counter: None,
+            signal_strength: None,
+            fields: std::collections::HashMap::new(),
+        };
+
+        let mut decoded_any = false;
+        let mut i = 0;
+        while i < records.len() {
+            let object_id = records[i];
+            i += 1;
+            match object_id {
+                // Packet id: a rolling counter most BTHome v2 advertisers send
+                // first. Fixed 1-byte width; we don't currently surface it.
+                OBJ_PACKET_ID => {
+                    i += 1;
+                }
+                OBJ_BATTERY => {
+                    sensor_data.battery_level = Some(*records.get(i)?);
+                    i += 1;
+                    decoded_any = true;
+                }
+                OBJ_TEMPERATURE => {
+                    let raw = i16::from_le_bytes([*records.get(i)?, *records.get(i + 1)?]);
+                    sensor_data.temperature = Some(f32::from(raw) / 100.0);
+                    i += 2;
+                    decoded_any = true;
+                }
+                OBJ_HUMIDITY => {
+                    let raw = u16::from_le_bytes([*records.get(i)?, *records.get(i + 1)?]);
+                    sensor_data.humidity = Some(f32::from(raw) / 100.0);
+                    i += 2;
+                    decoded_any = true;
+                }
+                OBJ_VOLTAGE => {
+                    let raw = u16::from_le_bytes([*records.get(i)?, *records.get(i + 1)?]);
+                    sensor_data.battery_voltage = Some(f32::from(raw) / 1000.0);
+                    i += 2;
+                    decoded_any = true;
+                }
+                // Unknown object id: without its width we can't safely skip
+                // past it, so stop parsing and keep whatever we've read.
+                _ => break,
+            }
+        }
+
+        if !decoded_any {
+            return None;
+        }
+
+        Some(sensor_data)
+    }
+}
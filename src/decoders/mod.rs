@@ -0,0 +1,59 @@
+use uuid::Uuid;
+
+use crate::SensorData;
+
+mod bthome;
+mod pvvx;
+
+pub use bthome::BthomeDecoder;
+pub use pvvx::PvvxDecoder;
+
+const BLE_BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_00805F9B34FB;
+
+/// Expands a Bluetooth SIG 16-bit service UUID to its full 128-bit form.
+pub(crate) fn sig_uuid16(short: u16) -> Uuid {
+    Uuid::from_u128(BLE_BASE_UUID | ((short as u128) << 96))
+}
+
+/// Decodes the service-data payload of a BLE advertisement into a reading.
+///
+/// Implementors declare the service-data UUIDs they understand via
+/// `service_uuids` and are consulted by the `DecoderRegistry` whenever a
+/// `ServiceDataAdvertisement` carries a matching UUID.
+pub trait AdvDecoder {
+    /// Service-data UUIDs this decoder knows how to handle.
+    fn service_uuids(&self) -> &[Uuid];
+
+    /// Attempt to decode a service-data payload advertised under one of
+    /// `service_uuids`. Returns `None` if the payload is malformed,
+    /// encrypted, or otherwise not a reading this decoder understands.
+    fn decode(&self, data: &[u8]) -> Option<SensorData>;
+}
+
+/// Holds the known `AdvDecoder`s and dispatches a service-data payload to
+/// whichever one is registered for the advertised UUID.
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn AdvDecoder>>,
+}
+
+impl DecoderRegistry {
+    pub fn new() -> Self {
+        Self {
+            decoders: vec![Box::new(PvvxDecoder::new()), Box::new(BthomeDecoder::new())],
+        }
+    }
+
+    /// Decode `data` using whichever registered decoder claims `uuid`.
+    pub fn decode(&self, uuid: &Uuid, data: &[u8]) -> Option<SensorData> {
+        self.decoders
+            .iter()
+            .find(|d| d.service_uuids().contains(uuid))?
+            .decode(data)
+    }
+}
+
+impl Default for DecoderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,66 @@
+use uuid::Uuid;
+
+use crate::SensorData;
+
+use super::{sig_uuid16, AdvDecoder};
+
+/// Decodes the custom pvvx/Nozzytronics service-data layout used by
+/// ATC-firmware thermometers (service UUID 0x181A).
+pub struct PvvxDecoder {
+    uuids: Vec<Uuid>,
+}
+
+impl PvvxDecoder {
+    pub fn new() -> Self {
+        Self {
+            uuids: vec![sig_uuid16(0x181A)],
+        }
+    }
+}
+
+impl Default for PvvxDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdvDecoder for PvvxDecoder {
+    fn service_uuids(&self) -> &[Uuid] {
+        &self.uuids
+    }
+
+    fn decode(&self, data: &[u8]) -> Option<SensorData> {
+        // All data little endian
+        // uint8_t     MAC[6]; // [0] - lo, .. [5] - hi digits
+        // int16_t     temperature;    // x 0.01 degree     [6,7]
+        // uint16_t    humidity;       // x 0.01 %          [8,9]
+        // uint16_t    battery_mv;     // mV                [10,11]
+        // uint8_t     battery_level;  // 0..100 %          [12]
+        // uint8_t     counter;        // measurement count [13]
+        // uint8_t     flags;  [14]
+        if data.len() < 15 {
+            return None;
+        }
+
+        let mac = format!(
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            data[5], data[4], data[3], data[2], data[1], data[0]
+        );
+        let temp = f32::from(i16::from_le_bytes([data[6], data[7]])) / 100.0;
+        let hum = f32::from(u16::from(data[8]) | (u16::from(data[9]) << 8)) / 100.0;
+        let battery_v = f32::from(u16::from(data[10]) | (u16::from(data[11]) << 8)) / 1000.0;
+        let battery_level = data[12];
+        let counter = data[13];
+
+        Some(SensorData {
+            mac_address: mac,
+            temperature: Some(temp),
+            humidity: Some(hum),
+            battery_level: Some(battery_level),
+            battery_voltage: Some(battery_v),
+            counter: Some(counter),
+            signal_strength: None,
+            fields: std::collections::HashMap::new(),
+        })
+    }
+}
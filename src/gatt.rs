@@ -0,0 +1,153 @@
+// Connect-and-notify subsystem for sensors that don't beacon their readings
+// in advertisements (e.g. Mijia LYWSD03MMC on stock firmware). Runs alongside
+// the passive `ServiceDataAdvertisement` scan loop in `main`, feeding decoded
+// readings into the same `publish` path.
+
+use btleplug::api::{Central, Peripheral as _};
+use btleplug::platform::{Adapter, Peripheral};
+use futures::stream::StreamExt;
+use paho_mqtt as mqtt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::influxdb::InfluxSink;
+use crate::{handle_reading, Sensor, SensorData};
+
+/// Notify characteristic exposed by stock Xiaomi Mijia LYWSD03MMC firmware,
+/// carrying temperature/humidity/battery in a single 5-byte payload.
+const MIJIA_NOTIFY_CHARACTERISTIC: Uuid = Uuid::from_u128(0xebe0ccc1_7a0a_4b0c_8a1a_6ff2997da3a6);
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const DISCOVERY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches every sensor configured with `mode: "connect"`, reconnecting
+/// automatically whenever a device drops off.
+pub async fn run(
+    central: Adapter,
+    sensors: Vec<Sensor>,
+    mqtt_client: mqtt::AsyncClient,
+    influx_sink: Option<InfluxSink>,
+    last_seen: Arc<Mutex<HashMap<String, Instant>>>,
+) {
+    let watchers = sensors.into_iter().map(|sensor| {
+        let central = central.clone();
+        let mqtt_client = mqtt_client.clone();
+        let influx_sink = influx_sink.clone();
+        let last_seen = last_seen.clone();
+        watch_sensor(central, sensor, mqtt_client, influx_sink, last_seen)
+    });
+
+    futures::future::join_all(watchers).await;
+}
+
+async fn watch_sensor(
+    central: Adapter,
+    sensor: Sensor,
+    mqtt_client: mqtt::AsyncClient,
+    influx_sink: Option<InfluxSink>,
+    last_seen: Arc<Mutex<HashMap<String, Instant>>>,
+) {
+    loop {
+        if let Err(err) = connect_and_listen(
+            &central,
+            &sensor,
+            &mqtt_client,
+            influx_sink.as_ref(),
+            &last_seen,
+        )
+        .await
+        {
+            error!("Connect-mode sensor {} errored: {}", sensor.mac, err);
+        }
+        info!("Reconnecting to {} in {:?}", sensor.mac, RECONNECT_DELAY);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn connect_and_listen(
+    central: &Adapter,
+    sensor: &Sensor,
+    mqtt_client: &mqtt::AsyncClient,
+    influx_sink: Option<&InfluxSink>,
+    last_seen: &Mutex<HashMap<String, Instant>>,
+) -> Result<(), Box<dyn Error>> {
+    let peripheral = find_peripheral(central, &sensor.mac).await?;
+
+    peripheral.connect().await?;
+    peripheral.discover_services().await?;
+
+    let characteristic = peripheral
+        .characteristics()
+        .into_iter()
+        .find(|c| c.uuid == MIJIA_NOTIFY_CHARACTERISTIC)
+        .ok_or("Mijia notify characteristic not found")?;
+
+    peripheral.subscribe(&characteristic).await?;
+
+    let mut notifications = peripheral.notifications().await?;
+    while let Some(data) = notifications.next().await {
+        if data.uuid != MIJIA_NOTIFY_CHARACTERISTIC {
+            continue;
+        }
+
+        if let Some(sensor_data) = decode_mijia(&data.value, &sensor.mac) {
+            handle_reading(
+                mqtt_client,
+                influx_sink,
+                std::slice::from_ref(sensor),
+                last_seen,
+                sensor_data,
+            )
+            .await?;
+        }
+    }
+
+    Err("notification stream ended".into())
+}
+
+/// Finds `mac` among the adapter's known peripherals, waiting for it to show
+/// up in at least one advertisement if it hasn't yet.
+async fn find_peripheral(central: &Adapter, mac: &str) -> Result<Peripheral, Box<dyn Error>> {
+    loop {
+        for peripheral in central.peripherals().await? {
+            if let Some(props) = peripheral.properties().await? {
+                if props.address.to_string().eq_ignore_ascii_case(mac) {
+                    return Ok(peripheral);
+                }
+            }
+        }
+        tokio::time::sleep(DISCOVERY_POLL_INTERVAL).await;
+    }
+}
+
+/// Decodes the 5-byte notify payload exposed by stock Mijia firmware:
+/// int16 LE temperature x0.01 degree [0,1], uint8 humidity % [2],
+/// uint16 LE battery millivolts [3,4].
+fn decode_mijia(value: &[u8], mac: &str) -> Option<SensorData> {
+    if value.len() < 5 {
+        return None;
+    }
+
+    let temperature = f32::from(i16::from_le_bytes([value[0], value[1]])) / 100.0;
+    let humidity = f32::from(value[2]);
+    let battery_mv = u16::from_le_bytes([value[3], value[4]]);
+    let battery_voltage = f32::from(battery_mv) / 1000.0;
+    // Roughly linear between empty (2.1V) and full (3.1V), as used by most
+    // community Mijia integrations in the absence of a reported percentage.
+    let battery_level = (((battery_voltage - 2.1) / (3.1 - 2.1)) * 100.0).clamp(0.0, 100.0) as u8;
+
+    Some(SensorData {
+        mac_address: mac.to_string(),
+        temperature: Some(temperature),
+        humidity: Some(humidity),
+        battery_level: Some(battery_level),
+        battery_voltage: Some(battery_voltage),
+        counter: None,
+        signal_strength: None,
+        fields: std::collections::HashMap::new(),
+    })
+}
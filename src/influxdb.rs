@@ -0,0 +1,93 @@
+// Optional InfluxDB line-protocol output, parallel to the MQTT `publish`
+// path. Independently enable-able: absent from `Config` entirely disables
+// it, with no effect on the MQTT side.
+
+use reqwest::Client;
+use serde::Deserialize;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Sensor, SensorData};
+
+#[derive(Deserialize, Clone)]
+pub struct InfluxConfig {
+    pub url: String,
+    pub database: String,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Writes sensor readings to an InfluxDB `/write` endpoint as line protocol.
+#[derive(Clone)]
+pub struct InfluxSink {
+    config: InfluxConfig,
+    client: Client,
+}
+
+impl InfluxSink {
+    pub fn new(config: InfluxConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+        }
+    }
+
+    pub async fn publish(&self, sensor: &Sensor, sd: &SensorData) -> Result<(), Box<dyn Error>> {
+        let line = to_line_protocol(sensor, sd);
+        let url = format!("{}/write?db={}", self.config.url, self.config.database);
+
+        let mut request = self.client.post(url).body(line);
+        if let Some(token) = &self.config.token {
+            request = request.bearer_auth(token);
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+fn to_line_protocol(sensor: &Sensor, sd: &SensorData) -> String {
+    let timestamp_ns = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    // Sensors with a configured `layout` (chunk0-3) leave the fixed fields
+    // `None` and report their real readings via `fields` instead; only emit
+    // a fixed field when the decoder actually populated it, or we'd write
+    // bogus zero series alongside the real values.
+    let mut fields = Vec::new();
+    if let Some(temperature) = sd.temperature {
+        fields.push(format!("temperature={}", temperature));
+    }
+    if let Some(humidity) = sd.humidity {
+        fields.push(format!("humidity={}", humidity));
+    }
+    if let Some(battery_voltage) = sd.battery_voltage {
+        fields.push(format!("battery_voltage={}", battery_voltage));
+    }
+    if let Some(battery_level) = sd.battery_level {
+        fields.push(format!("battery_level={}i", battery_level));
+    }
+    for (name, value) in &sd.fields {
+        fields.push(format!("{}={}", escape_identifier(name), value));
+    }
+
+    format!(
+        "sensor,mac={},name={} {} {}",
+        escape_identifier(&sd.mac_address),
+        escape_identifier(&sensor.name),
+        fields.join(","),
+        timestamp_ns
+    )
+}
+
+/// Escapes a tag key/value or field key for line protocol, where spaces,
+/// commas, and equals signs are syntactically significant.
+fn escape_identifier(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+        .replace('=', "\\=")
+}
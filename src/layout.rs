@@ -0,0 +1,83 @@
+// Config-driven binary layout parsing, for sensors whose advertisement
+// payload doesn't match one of the built-in `AdvDecoder`s exactly (e.g.
+// firmware forks with swapped fields or different scaling).
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Binary representation of a single field within a payload.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    U8,
+    S16,
+    U16,
+    U32,
+    S32,
+}
+
+impl FieldType {
+    fn width(self) -> usize {
+        match self {
+            FieldType::U8 => 1,
+            FieldType::S16 | FieldType::U16 => 2,
+            FieldType::U32 | FieldType::S32 => 4,
+        }
+    }
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// Describes where one value lives in a sensor's advertised payload and how
+/// to present it, mirroring how register-mapping MQTT bridges describe
+/// values.
+#[derive(Deserialize, Clone)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub offset: usize,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub swap_words: bool,
+    pub unit: String,
+    pub device_class: String,
+}
+
+fn read_raw(data: &[u8], offset: usize, field_type: FieldType, swap_words: bool) -> Option<i64> {
+    let width = field_type.width();
+    let bytes = data.get(offset..offset + width)?;
+
+    Some(match field_type {
+        FieldType::U8 => i64::from(bytes[0]),
+        FieldType::S16 => i64::from(i16::from_le_bytes([bytes[0], bytes[1]])),
+        FieldType::U16 => i64::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+        FieldType::U32 | FieldType::S32 => {
+            let mut word_bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if swap_words {
+                word_bytes.swap(0, 2);
+                word_bytes.swap(1, 3);
+            }
+            if field_type == FieldType::S32 {
+                i64::from(i32::from_le_bytes(word_bytes))
+            } else {
+                i64::from(u32::from_le_bytes(word_bytes))
+            }
+        }
+    })
+}
+
+/// Reads every field in `layout` out of `data`, scaling each into a
+/// name-keyed map. Fields whose offset falls outside `data` are skipped.
+pub fn decode(data: &[u8], layout: &[FieldDescriptor]) -> HashMap<String, f64> {
+    let mut fields = HashMap::new();
+    for field in layout {
+        if let Some(raw) = read_raw(data, field.offset, field.field_type, field.swap_words) {
+            fields.insert(field.name.clone(), raw as f64 * field.scale);
+        }
+    }
+    fields
+}
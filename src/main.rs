@@ -10,22 +10,114 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
 #[macro_use]
 extern crate log;
 
+mod decoders;
+mod gatt;
+mod influxdb;
+mod layout;
+mod watchdog;
+
+use decoders::DecoderRegistry;
+use influxdb::{InfluxConfig, InfluxSink};
+use layout::FieldDescriptor;
+
 #[derive(Deserialize)]
 struct Config {
     broker: String,
     sensors: Vec<Sensor>,
+    #[serde(default)]
+    influxdb: Option<InfluxConfig>,
+    #[serde(default)]
+    discovery: DiscoveryConfig,
+    /// Seconds a configured sensor may go without advertising before its
+    /// per-device availability topic is flipped to "offline". Absent
+    /// disables the watchdog entirely.
+    #[serde(default)]
+    timeout_secs: Option<u64>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+struct DiscoveryConfig {
+    enabled: bool,
+    prefix: String,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
 struct Sensor {
     mac: String,
     name: String,
+    #[serde(default)]
+    mode: SensorMode,
+    /// Custom payload layout for this sensor's advertisement, for firmware
+    /// that doesn't match one of the built-in decoders exactly. Overrides
+    /// the registry for this MAC's `ServiceDataAdvertisement`s.
+    #[serde(default)]
+    layout: Option<Vec<FieldDescriptor>>,
+}
+
+/// `SensorData`'s fixed field names, reserved because `fields` is flattened
+/// into the same JSON object; a layout field reusing one would silently
+/// clobber the fixed key.
+const BUILTIN_FIELD_NAMES: &[&str] = &[
+    "mac_address",
+    "temperature",
+    "humidity",
+    "battery_level",
+    "battery_voltage",
+    "counter",
+    "signal_strength",
+];
+
+/// Rejects sensor configs whose custom `layout` field names collide with a
+/// `SensorData` fixed field.
+fn validate_sensor_layouts(sensors: &[Sensor]) -> Result<(), String> {
+    for sensor in sensors {
+        if let Some(layout) = &sensor.layout {
+            for field in layout {
+                if BUILTIN_FIELD_NAMES.contains(&field.name.as_str()) {
+                    return Err(format!(
+                        "sensor '{}': layout field name '{}' collides with a built-in SensorData field",
+                        sensor.name, field.name
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How a sensor's readings reach the scanner.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SensorMode {
+    /// Passively decoded from `ServiceDataAdvertisement` events (the default).
+    Advertise,
+    /// Connected to over GATT and read from notifications; for sensors that
+    /// don't broadcast readings in their advertisements.
+    Connect,
+}
+
+impl Default for SensorMode {
+    fn default() -> Self {
+        SensorMode::Advertise
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -54,14 +146,28 @@ struct SensorDiscoveryPayload {
     device: DeviceDiscoveryPayload,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct SensorData {
     mac_address: String,
-    temperature: f32,
-    humidity: f32,
-    battery_level: u8,
-    battery_voltage: f32,
-    counter: u8,
+    /// `None` for sensors decoded via a configured `layout`, whose readings
+    /// live only in `fields`. Built-in decoders set only the readings their
+    /// payload carries (e.g. a BTHome packet may omit any of them).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    humidity: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    battery_level: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    battery_voltage: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    counter: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal_strength: Option<i16>,
+    /// Values read via a sensor's configured `layout`, keyed by field name.
+    /// Empty for sensors using a built-in decoder.
+    #[serde(flatten)]
+    fields: HashMap<String, f64>,
 }
 
 async fn get_central(manager: &Manager) -> Adapter {
@@ -69,46 +175,50 @@ async fn get_central(manager: &Manager) -> Adapter {
     adapters.into_iter().nth(0).unwrap()
 }
 
-fn parse_the_stuff(value: Vec<u8>) -> SensorData {
-    /*
-    All data little endian
-    uint8_t     MAC[6]; // [0] - lo, .. [5] - hi digits
-    int16_t     temperature;    // x 0.01 degree     [6,7]
-    uint16_t    humidity;       // x 0.01 %          [8,9]
-    uint16_t    battery_mv;     // mV                [10,11]
-    uint8_t     battery_level;  // 0..100 %          [12]
-    uint8_t     counter;        // measurement count [13]
-    uint8_t     flags;  [14]
-    */
+/// If `data` carries the MAC of a configured sensor with a custom `layout`
+/// in its usual first-6-bytes position, decode it with that layout instead
+/// of the built-in decoder registry.
+///
+/// This assumes every custom layout's advertisement puts its MAC at bytes
+/// `[0..6]` in reversed (pvvx-style) byte order, the same as the built-in
+/// `PvvxDecoder`. A sensor whose firmware places the MAC elsewhere (or
+/// omits it, like BTHome) won't match here and silently falls through to
+/// the decoder registry instead.
+fn match_configured_layout(sensors: &[Sensor], data: &[u8]) -> Option<SensorData> {
+    if data.len() < 6 {
+        return None;
+    }
+
     let mac = format!(
         "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-        value[5], value[4], value[3], value[2], value[1], value[0]
+        data[5], data[4], data[3], data[2], data[1], data[0]
     );
-    let temp = f32::from(u16::from(value[6]) | (u16::from(value[7]) << 8)) / 100.0;
-    let hum = f32::from(u16::from(value[8]) | (u16::from(value[9]) << 8)) / 100.0;
-    let battery_v = f32::from(u16::from(value[10]) | (u16::from(value[11]) << 8)) / 1000.0;
-    let battery_level = value[12];
-    let counter = value[13];
+    let sensor = sensors.iter().find(|s| s.mac == mac)?;
+    let layout = sensor.layout.as_ref()?;
 
-    SensorData {
+    Some(SensorData {
         mac_address: mac,
-        temperature: temp,
-        humidity: hum,
-        battery_level: battery_level,
-        battery_voltage: battery_v,
-        counter: counter,
-    }
+        temperature: None,
+        humidity: None,
+        battery_level: None,
+        battery_voltage: None,
+        counter: None,
+        signal_strength: None,
+        fields: layout::decode(data, layout),
+    })
 }
 
-async fn publish(
-    client: &mqtt::AsyncClient,
-    availability_topic: &String,
-    sd: SensorData,
-) -> Result<(), Box<dyn Error>> {
-    info!("Publishing: {} for {}", sd.temperature, sd.mac_address);
+/// The MQTT topic a sensor's per-device availability ("online"/"offline")
+/// is published to, independent of the scanner process's own LWT.
+pub(crate) fn sensor_availability_topic(mac: &str) -> String {
+    format!("home/sensor/mac/{}/availability", mac)
+}
+
+async fn publish(client: &mqtt::AsyncClient, sd: SensorData) -> Result<(), Box<dyn Error>> {
+    info!("Publishing: {:?} for {}", sd.temperature, sd.mac_address);
 
     client.publish(mqtt::Message::new(
-        availability_topic,
+        sensor_availability_topic(&sd.mac_address),
         "online",
         mqtt::QOS_1,
     ));
@@ -120,13 +230,47 @@ async fn publish(
     Ok(())
 }
 
+/// Accepts a reading that's passed its decoder/config checks: stamps
+/// `last_seen` for the watchdog, forwards it to the optional InfluxDB sink,
+/// then publishes it over MQTT. Shared by the advertisement scan loop and
+/// the GATT connect-mode watchers so every accepted reading, however it
+/// reached the process, is fanned out to the same sinks and tracked the
+/// same way.
+pub(crate) async fn handle_reading(
+    mqtt_client: &mqtt::AsyncClient,
+    influx_sink: Option<&InfluxSink>,
+    sensors: &[Sensor],
+    last_seen: &Mutex<HashMap<String, Instant>>,
+    sensor_data: SensorData,
+) -> Result<(), Box<dyn Error>> {
+    last_seen
+        .lock()
+        .await
+        .insert(sensor_data.mac_address.clone(), Instant::now());
+
+    if let Some(sink) = influx_sink {
+        if let Some(sensor) = sensors.iter().find(|s| s.mac == sensor_data.mac_address) {
+            if let Err(err) = sink.publish(sensor, &sensor_data).await {
+                error!("Failed to publish to InfluxDB: {}", err);
+            }
+        }
+    }
+
+    publish(mqtt_client, sensor_data).await
+}
+
 async fn setup_autodiscovery(
     sensors: &Vec<Sensor>,
-    availability_topic: &String,
+    discovery: &DiscoveryConfig,
     mqtt: &mqtt::AsyncClient,
 ) -> Result<(), Box<dyn Error>> {
+    if !discovery.enabled {
+        return Ok(());
+    }
+
     for s in sensors {
         let ident = s.mac.replace(":", "");
+        let availability_topic = sensor_availability_topic(&s.mac);
 
         let device = DeviceDiscoveryPayload {
             manufacturer: "Nozzytronics".to_string(),
@@ -136,9 +280,47 @@ async fn setup_autodiscovery(
 
         send(
             mqtt,
+            &discovery.prefix,
+            SensorDiscoveryPayload {
+                name: format!("{}-signalstrength", s.name),
+                availability_topic: availability_topic.clone(),
+                device_class: "signal_strength".to_string(),
+                state_topic: format!("home/sensor/mac/{}/info", s.mac),
+                unit_of_measurement: "dBm".to_string(),
+                value_template: "{{value_json.signal_strength}}".to_string(),
+                unique_id: format!("{}-signalstrength", s.name),
+                device: device.clone(),
+            },
+        )
+        .await?;
+
+        if let Some(layout) = &s.layout {
+            for field in layout {
+                send(
+                    mqtt,
+                    &discovery.prefix,
+                    SensorDiscoveryPayload {
+                        name: format!("{}-{}", s.name, field.name),
+                        availability_topic: availability_topic.clone(),
+                        device_class: field.device_class.clone(),
+                        state_topic: format!("home/sensor/mac/{}/info", s.mac),
+                        unit_of_measurement: field.unit.clone(),
+                        value_template: format!("{{{{value_json.{}}}}}", field.name),
+                        unique_id: format!("{}-{}", s.name, field.name),
+                        device: device.clone(),
+                    },
+                )
+                .await?;
+            }
+            continue;
+        }
+
+        send(
+            mqtt,
+            &discovery.prefix,
             SensorDiscoveryPayload {
                 name: format!("{}-temp", s.name),
-                availability_topic: availability_topic,
+                availability_topic: availability_topic.clone(),
                 device_class: "temperature".to_string(),
                 state_topic: format!("home/sensor/mac/{}/info", s.mac),
                 unit_of_measurement: "Â°C".to_string(),
@@ -151,9 +333,10 @@ async fn setup_autodiscovery(
 
         send(
             mqtt,
+            &discovery.prefix,
             SensorDiscoveryPayload {
                 name: format!("{}-humidity", s.name),
-                availability_topic: availability_topic,
+                availability_topic: availability_topic.clone(),
                 device_class: "humidity".to_string(),
                 state_topic: format!("home/sensor/mac/{}/info", s.mac),
                 unit_of_measurement: "%".to_string(),
@@ -166,9 +349,10 @@ async fn setup_autodiscovery(
 
         send(
             mqtt,
+            &discovery.prefix,
             SensorDiscoveryPayload {
                 name: format!("{}-batteryvoltage", s.name),
-                availability_topic: availability_topic,
+                availability_topic: availability_topic.clone(),
                 device_class: "voltage".to_string(),
                 state_topic: format!("home/sensor/mac/{}/info", s.mac),
                 unit_of_measurement: "V".to_string(),
@@ -181,9 +365,10 @@ async fn setup_autodiscovery(
 
         send(
             mqtt,
+            &discovery.prefix,
             SensorDiscoveryPayload {
                 name: format!("{}-batterylevel", s.name),
-                availability_topic: availability_topic,
+                availability_topic,
                 device_class: "battery".to_string(),
                 state_topic: format!("home/sensor/mac/{}/info", s.mac),
                 unit_of_measurement: "%".to_string(),
@@ -199,15 +384,77 @@ async fn setup_autodiscovery(
 
 async fn send(
     mqtt: &mqtt::AsyncClient,
+    prefix: &str,
     payload: SensorDiscoveryPayload,
 ) -> Result<(), Box<dyn Error>> {
-    let topic = format!("homeassistant/sensor/{}/config", payload.unique_id);
+    let topic = format!("{}/sensor/{}/config", prefix, payload.unique_id);
     let json = serde_json::to_string(&payload)?;
 
-    let message = mqtt::Message::new(topic, json, mqtt::QOS_1);
+    let message = mqtt::Message::new_retained(topic, json, mqtt::QOS_1);
     Ok(mqtt.publish(message).await?)
 }
 
+/// The set of Home Assistant discovery `unique_id`s that would currently be
+/// published for `sensors`, used to detect stale entries left behind when a
+/// sensor is removed from the config.
+fn discovery_unique_ids(sensors: &[Sensor]) -> Vec<String> {
+    let mut ids = Vec::new();
+    for s in sensors {
+        ids.push(format!("{}-signalstrength", s.name));
+        if let Some(layout) = &s.layout {
+            for field in layout {
+                ids.push(format!("{}-{}", s.name, field.name));
+            }
+        } else {
+            for suffix in ["temp", "humidity", "batteryvoltage", "batterylevel"] {
+                ids.push(format!("{}-{}", s.name, suffix));
+            }
+        }
+    }
+    ids
+}
+
+/// Publishes an empty retained message to each `unique_id`'s discovery
+/// topic, so Home Assistant removes the corresponding entity.
+async fn clear_discovery(
+    mqtt: &mqtt::AsyncClient,
+    prefix: &str,
+    unique_ids: &[String],
+) -> Result<(), Box<dyn Error>> {
+    for unique_id in unique_ids {
+        let topic = format!("{}/sensor/{}/config", prefix, unique_id);
+        let message = mqtt::Message::new_retained(topic, "", mqtt::QOS_1);
+        mqtt.publish(message).await?;
+    }
+    Ok(())
+}
+
+/// Path of the side-car file that remembers, across restarts, which
+/// discovery `unique_id`s were published for this config.
+///
+/// This is how removed-sensor cleanup actually happens: `main` diffs this
+/// file's contents against the current config's `unique_id`s at startup and
+/// clears whatever's no longer present (see the `clear_discovery` call
+/// above `setup_autodiscovery`). A clean shutdown (Ctrl-C) deliberately does
+/// *not* clear discovery itself — doing so would delete every live entity
+/// on a routine restart, not just ones removed from the config.
+fn discovery_state_path(config_path: &str) -> String {
+    format!("{}.discovered.json", config_path)
+}
+
+fn load_known_unique_ids(path: &str) -> Vec<String> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save_known_unique_ids(path: &str, unique_ids: &[String]) {
+    if let Ok(json) = serde_json::to_string(unique_ids) {
+        let _ = fs::write(path, json);
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init();
@@ -216,6 +463,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let config_data = fs::read_to_string(args.config).expect("Unable to read config file");
 
     let config: Config = serde_json::from_str(&config_data).expect("Unable to parse config");
+    validate_sensor_layouts(&config.sensors).expect("Invalid sensor layout");
 
     let uuid = Uuid::new_v4();
     let availability_topic = format!("home/sensor/uuid/{}/availability", uuid);
@@ -246,8 +494,28 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     mqtt_client.connect(mqtt_conn_opt).await?;
 
+    let discovery_state_path = discovery_state_path(&args.config);
+    let current_unique_ids = discovery_unique_ids(&config.sensors);
+
+    if config.discovery.enabled {
+        let known_unique_ids = load_known_unique_ids(&discovery_state_path);
+        let stale_unique_ids: Vec<String> = known_unique_ids
+            .into_iter()
+            .filter(|id| !current_unique_ids.contains(id))
+            .collect();
+
+        if !stale_unique_ids.is_empty() {
+            info!(
+                "Removing {} stale discovery entries no longer in config",
+                stale_unique_ids.len()
+            );
+            clear_discovery(&mqtt_client, &config.discovery.prefix, &stale_unique_ids).await?;
+        }
+    }
+
     // Trigger autodiscovery
-    setup_autodiscovery(&config.sensors, &availability_topic, &mqtt_client).await?;
+    setup_autodiscovery(&config.sensors, &config.discovery, &mqtt_client).await?;
+    save_known_unique_ids(&discovery_state_path, &current_unique_ids);
 
     let manager = Manager::new().await?;
 
@@ -263,47 +531,134 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // start scanning for devices
     central.start_scan(ScanFilter::default()).await?;
 
+    let registry = DecoderRegistry::new();
+    let influx_sink = config.influxdb.clone().map(InfluxSink::new);
+
+    let connect_sensors: Vec<Sensor> = config
+        .sensors
+        .iter()
+        .filter(|s| s.mode == SensorMode::Connect)
+        .cloned()
+        .collect();
+
+    let last_seen: Arc<Mutex<HashMap<String, Instant>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    if !connect_sensors.is_empty() {
+        tokio::spawn(gatt::run(
+            central.clone(),
+            connect_sensors,
+            mqtt_client.clone(),
+            influx_sink.clone(),
+            last_seen.clone(),
+        ));
+    }
+
+    if let Some(timeout_secs) = config.timeout_secs {
+        tokio::spawn(watchdog::run(
+            config.sensors.clone(),
+            mqtt_client.clone(),
+            last_seen.clone(),
+            Duration::from_secs(timeout_secs),
+        ));
+    }
+
+    let mut rssi_by_id = HashMap::new();
+
     // Print based on whatever the event receiver outputs. Note that the event
     // receiver blocks, so in a real program, this should be run in its own
     // thread (not task, as this library does not yet use async channels).
-    while let Some(event) = events.next().await {
-        if let CentralEvent::ServiceDataAdvertisement {
-            id: _,
-            service_data,
-        } = event
-        {
-            for (key, value) in service_data.into_iter() {
-                let magic = key.as_bytes().windows(2).position(|s| s == [0x18, 0x1A]);
-                if let Some(_) = magic {
-                    // parse
-                    let sensor_data = parse_the_stuff(value);
-
-                    // filter
-                    let x = config
-                        .sensors
-                        .iter()
-                        .map(|s| s.mac.to_owned())
-                        .collect::<Vec<String>>();
-
-                    if !x.contains(&sensor_data.mac_address) {
-                        info!("Filtering out: {}", &sensor_data.mac_address);
-                        continue;
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                // Don't clear discovery on a routine restart — that would delete
+                // every live entity. Removed-sensor cleanup instead happens at
+                // startup via the `discovery_state_path` side-car diff; the
+                // per-sensor availability topic, flipped to "offline" here, is
+                // what tells Home Assistant this process has gone away.
+                info!("Shutting down");
+                for sensor in &config.sensors {
+                    let message = mqtt::Message::new(
+                        sensor_availability_topic(&sensor.mac),
+                        "offline",
+                        mqtt::QOS_1,
+                    );
+                    if let Err(err) = mqtt_client.publish(message).await {
+                        error!("Failed to publish offline for {}: {}", sensor.mac, err);
                     }
-
-                    // check if new
-                    let prev =
-                        counters.insert(sensor_data.mac_address.clone(), sensor_data.counter);
-                    if let Some(x) = prev {
-                        if sensor_data.counter == x {
-                            info!(
-                                "Skipping repeated measurement for {}",
-                                &sensor_data.mac_address
-                            );
-                            continue;
+                }
+                break;
+            }
+            event = events.next() => {
+                let Some(event) = event else {
+                    break;
+                };
+
+                match event {
+                    CentralEvent::RssiUpdate { id, rssi } => {
+                        rssi_by_id.insert(id, rssi);
+                    }
+                    CentralEvent::ServiceDataAdvertisement { id, service_data } => {
+                        for (uuid, value) in service_data.into_iter() {
+                            let mut sensor_data = match match_configured_layout(&config.sensors, &value)
+                                .or_else(|| registry.decode(&uuid, &value))
+                            {
+                                Some(sensor_data) => sensor_data,
+                                None => continue,
+                            };
+
+                            // Some decoders (e.g. BTHome) don't carry the MAC in the
+                            // payload itself, so fall back to the advertisement's id.
+                            if sensor_data.mac_address.is_empty() {
+                                sensor_data.mac_address = id.to_string();
+                            }
+                            sensor_data.signal_strength = rssi_by_id.get(&id).copied();
+
+                            // Filter to configured sensors, matching case-insensitively
+                            // since btleplug's id Display renders uppercase while
+                            // configured/pvvx-formatted MACs are lowercase (as gatt.rs's
+                            // find_peripheral already does). Adopt the configured casing
+                            // so later topics (keyed off `s.mac` in setup_autodiscovery)
+                            // line up with what we publish here.
+                            match config
+                                .sensors
+                                .iter()
+                                .find(|s| s.mac.eq_ignore_ascii_case(&sensor_data.mac_address))
+                            {
+                                Some(sensor) => sensor_data.mac_address = sensor.mac.clone(),
+                                None => {
+                                    info!("Filtering out: {}", &sensor_data.mac_address);
+                                    continue;
+                                }
+                            }
+
+                            last_seen
+                                .lock()
+                                .await
+                                .insert(sensor_data.mac_address.clone(), Instant::now());
+
+                            // check if new
+                            let prev = counters.insert(sensor_data.mac_address.clone(), sensor_data.counter);
+                            if let (Some(counter), Some(Some(prev_counter))) = (sensor_data.counter, prev) {
+                                if counter == prev_counter {
+                                    info!(
+                                        "Skipping repeated measurement for {}",
+                                        &sensor_data.mac_address
+                                    );
+                                    continue;
+                                }
+                            }
+
+                            handle_reading(
+                                &mqtt_client,
+                                influx_sink.as_ref(),
+                                &config.sensors,
+                                &last_seen,
+                                sensor_data,
+                            )
+                            .await?;
                         }
                     }
-
-                    publish(&mqtt_client, &availability_topic, sensor_data).await?;
+                    _ => {}
                 }
             }
         }
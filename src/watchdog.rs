@@ -0,0 +1,61 @@
+// Background online/offline tracking for sensors that are expected to
+// advertise (or notify) regularly. `main` timestamps every reading it
+// accepts into a shared `last_seen` map; this task periodically compares
+// those timestamps against `timeout` and flips each sensor's availability
+// topic accordingly.
+
+use paho_mqtt as mqtt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::{sensor_availability_topic, Sensor};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Polls `last_seen` every [`CHECK_INTERVAL`] and publishes "offline" to a
+/// sensor's availability topic once it's gone longer than `timeout` without
+/// a reading, and "online" again once a reading comes back in.
+pub async fn run(
+    sensors: Vec<Sensor>,
+    mqtt_client: mqtt::AsyncClient,
+    last_seen: Arc<Mutex<HashMap<String, Instant>>>,
+    timeout: Duration,
+) {
+    let mut online: HashMap<String, bool> = sensors.iter().map(|s| (s.mac.clone(), true)).collect();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    let started = Instant::now();
+
+    loop {
+        interval.tick().await;
+
+        let last_seen = last_seen.lock().await;
+        for sensor in &sensors {
+            // A sensor not yet heard from is measured from when the watchdog
+            // started, so it gets the same `timeout` grace as any other.
+            let is_online = last_seen
+                .get(&sensor.mac)
+                .map_or(started.elapsed() < timeout, |seen| seen.elapsed() < timeout);
+
+            if online.get(&sensor.mac).copied() != Some(is_online) {
+                info!(
+                    "Sensor {} is now {}",
+                    sensor.mac,
+                    if is_online { "online" } else { "offline" }
+                );
+                publish_availability(&mqtt_client, &sensor.mac, is_online);
+                online.insert(sensor.mac.clone(), is_online);
+            }
+        }
+    }
+}
+
+fn publish_availability(mqtt_client: &mqtt::AsyncClient, mac: &str, is_online: bool) {
+    let message = mqtt::Message::new(
+        sensor_availability_topic(mac),
+        if is_online { "online" } else { "offline" },
+        mqtt::QOS_1,
+    );
+    mqtt_client.publish(message);
+}